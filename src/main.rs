@@ -1,14 +1,23 @@
+use async_trait::async_trait;
+use base64::Engine;
 use clap::Parser;
-use curl::easy::Easy;
+use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::fs::File;
 use std::io::{Read, Write};
-use std::sync::mpsc::{channel, Sender, Receiver};
-use std::thread::scope;
+use std::process::Command;
 use std::time::Duration;
 
 const MOON_PHASES : [&str; 15] = [ "Óéç", "Óèñ", "Óèó", "Óèò", "Óèô", "Óèö", "Óèõ", "Óèú", "Óèù", "Óèû", "Óèü", "Óè†", "Óè°", "Óè¢", "Óéõ" ];
 
+/// Default number of tool-call round-trips allowed before giving up on a turn.
+const DEFAULT_MAX_TOOL_ITERATIONS: u32 = 8;
+
+/// Default number of times a dropped streaming connection is retried, with exponential backoff,
+/// before a turn gives up.
+const DEFAULT_MAX_RETRIES: u32 = 5;
+
 #[derive(Parser)]
 #[command(version, about)]
 struct InputOptions
@@ -23,6 +32,22 @@ struct InputOptions
     model: String,
     #[arg(short, long, default_value = "http://localhost:11434/api/chat", value_name = "URL")]
     endpoint: String,
+    #[arg(short, long, help = "Enable a local tool by name (repeatable); see --list-tools", value_name = "NAME")]
+    tool: Vec<String>,
+    #[arg(long, help = "List the locally implemented tools and exit")]
+    list_tools: bool,
+    #[arg(long, default_value_t = DEFAULT_MAX_TOOL_ITERATIONS, help = "Maximum number of tool-call round-trips per turn before giving up")]
+    max_tool_iterations: u32,
+    #[arg(long, help = "Attach an image to the outgoing message in file or prompt mode (repeatable); ignored in conversation mode", value_name = "PATH")]
+    image: Vec<String>,
+    #[arg(long, conflicts_with_all = ["file", "prompt"], help = "Load a saved conversation session before starting (conversation mode only)", value_name = "NAME")]
+    load: Option<String>,
+    #[arg(long, conflicts_with_all = ["file", "prompt"], help = "Save the conversation session under this name on exit (conversation mode only)", value_name = "NAME")]
+    save: Option<String>,
+    #[arg(long, help = "Emit newline-delimited JSON events on stdout instead of human-readable text")]
+    json: bool,
+    #[arg(long, default_value_t = DEFAULT_MAX_RETRIES, help = "Maximum number of reconnect attempts after a dropped streaming connection")]
+    max_retries: u32,
 }
 
 #[derive(Serialize, Deserialize, Default)]
@@ -62,11 +87,42 @@ struct ModelOptions
     num_thread: Option<u8>
 }
 
+#[derive(Serialize, Deserialize, Default, Clone)]
+struct ToolCallFunction
+{
+    name: String,
+    arguments: Value,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+struct ToolCall
+{
+    function: ToolCallFunction,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ToolFunctionDef
+{
+    name: String,
+    description: String,
+    parameters: Value,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ToolDef
+{
+    #[serde(rename = "type")]
+    tool_type: String,
+    function: ToolFunctionDef,
+}
+
 #[derive(Serialize, Deserialize, Default)]
 struct Message
 {
     role: String,
     content: String,
+    tool_calls: Option<Vec<ToolCall>>,
+    images: Option<Vec<String>>,
     done: Option<bool>,
     total_duration: Option<u64>,
     load_duration: Option<u64>,
@@ -91,135 +147,626 @@ struct LlamaRequest
     model: String,
     stream: bool,
     messages: Vec<Message>,
-    options: Option<ModelOptions>
+    options: Option<ModelOptions>,
+    tools: Option<Vec<ToolDef>>,
 }
 
-#[derive(Serialize, Deserialize, Default)]
-struct ErrorResponse
+/// A single event in the `--json` machine-readable output stream.
+#[derive(Serialize)]
+#[serde(tag = "type")]
+enum JsonEvent<'a>
 {
-    error: String
+    #[serde(rename = "token")]
+    Token { content: &'a str },
+    #[serde(rename = "message")]
+    Message {
+        content: &'a str,
+        total_duration: Option<u64>,
+        load_duration: Option<u64>,
+        prompt_eval_count: Option<u32>,
+        prompt_eval_duration: Option<u64>,
+        eval_count: Option<u32>,
+        eval_duration: Option<u64>,
+    },
+    #[serde(rename = "error")]
+    Error { message: &'a str },
+    #[serde(rename = "notice")]
+    Notice { message: &'a str },
 }
 
-struct ChannelMessage
+fn emit_json(event: &JsonEvent)
 {
-    /// Whether to expect more coming down the pipe
-    done: bool,
-    /// The chunk of data coming with this message
-    chunk: String,
+    println!("{}", serde_json::to_string(event).unwrap());
 }
 
-fn individual_request(request_object: &LlamaRequest, endpoint: &str) -> Result<String, String>
+/// Emit the terminal `message` event for a completed turn, carrying the assembled content and
+/// whatever timing/eval metadata the server attached to its final response.
+fn emit_final_message(content: &str, final_message: &Option<Message>)
 {
-    let data = serde_json::to_string(&request_object).unwrap();
+    emit_json(&JsonEvent::Message {
+        content,
+        total_duration: final_message.as_ref().and_then(|m| m.total_duration),
+        load_duration: final_message.as_ref().and_then(|m| m.load_duration),
+        prompt_eval_count: final_message.as_ref().and_then(|m| m.prompt_eval_count),
+        prompt_eval_duration: final_message.as_ref().and_then(|m| m.prompt_eval_duration),
+        eval_count: final_message.as_ref().and_then(|m| m.eval_count),
+        eval_duration: final_message.as_ref().and_then(|m| m.eval_duration),
+    });
+}
 
-    // Buffer to hold curl response data
-    let mut buf = Vec::new();
+/// A locally-executable function the model can invoke via Ollama's tool-calling protocol.
+///
+/// Execution is async and shares the caller's `reqwest::Client` so tools that make HTTP calls
+/// (e.g. [`HttpFetchTool`]) run on the existing Tokio runtime instead of blocking it.
+#[async_trait]
+trait Tool
+{
+    /// The schema advertised to the model in the request's `tools` field.
+    fn definition(&self) -> ToolDef;
+    /// Run the tool against the arguments the model supplied and return its textual result.
+    async fn execute(&self, client: &reqwest::Client, arguments: &Value) -> Result<String, String>;
+}
 
-    let mut curl_easy = Easy::new();
-    curl_easy.url(endpoint).unwrap();
+struct ShellTool;
 
-    curl_easy.read_function(move |into| {
-        Ok(data.as_bytes().read(into).unwrap())
-    }).unwrap();
-    curl_easy.post(true).unwrap();
+#[async_trait]
+impl Tool for ShellTool
+{
+    fn definition(&self) -> ToolDef
+    {
+        ToolDef {
+            tool_type: "function".to_string(),
+            function: ToolFunctionDef {
+                name: "shell".to_string(),
+                description: "Run a shell command on the local machine and return its combined stdout/stderr.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "command": { "type": "string", "description": "The shell command to execute" }
+                    },
+                    "required": ["command"]
+                }),
+            },
+        }
+    }
 
+    async fn execute(&self, _client: &reqwest::Client, arguments: &Value) -> Result<String, String>
     {
-        let mut transfer = curl_easy.transfer();
-        transfer.write_function(|data| {
-            let cl = buf.len();
-            buf.extend_from_slice(data);
-            Ok(buf.len() - cl)
-        }).unwrap();
+        let command = arguments.get("command")
+            .and_then(Value::as_str)
+            .ok_or_else(|| "missing required argument \"command\"".to_string())?;
 
-        match transfer.perform()
-        {
-            Ok(_) => { () }
-            Err(msg) => { eprintln!("{}", msg); return Err(msg.to_string()) }
-        };
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .output()
+            .map_err(|err| err.to_string())?;
+
+        let mut combined = String::from_utf8_lossy(&output.stdout).to_string();
+        combined.push_str(&String::from_utf8_lossy(&output.stderr));
+        Ok(combined)
     }
+}
 
-    let decoded = std::str::from_utf8(&buf).unwrap();
+struct FileReadTool;
 
-    match serde_json::from_str::<ErrorResponse>(&decoded)
+#[async_trait]
+impl Tool for FileReadTool
+{
+    fn definition(&self) -> ToolDef
     {
-        Ok(val) => {
-            eprintln!("Error received: {}", val.error);
-            return Err(val.error)
+        ToolDef {
+            tool_type: "function".to_string(),
+            function: ToolFunctionDef {
+                name: "file_read".to_string(),
+                description: "Read and return the contents of a local text file.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "Path of the file to read" }
+                    },
+                    "required": ["path"]
+                }),
+            },
         }
-        Err(_) => { () }
-    };
+    }
+
+    async fn execute(&self, _client: &reqwest::Client, arguments: &Value) -> Result<String, String>
+    {
+        let path = arguments.get("path")
+            .and_then(Value::as_str)
+            .ok_or_else(|| "missing required argument \"path\"".to_string())?;
+
+        std::fs::read_to_string(path).map_err(|err| err.to_string())
+    }
+}
+
+struct HttpFetchTool;
+
+#[async_trait]
+impl Tool for HttpFetchTool
+{
+    fn definition(&self) -> ToolDef
+    {
+        ToolDef {
+            tool_type: "function".to_string(),
+            function: ToolFunctionDef {
+                name: "http_fetch".to_string(),
+                description: "Fetch the body of a URL over HTTP(S) and return it as text.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "url": { "type": "string", "description": "The URL to fetch" }
+                    },
+                    "required": ["url"]
+                }),
+            },
+        }
+    }
+
+    async fn execute(&self, client: &reqwest::Client, arguments: &Value) -> Result<String, String>
+    {
+        let url = arguments.get("url")
+            .and_then(Value::as_str)
+            .ok_or_else(|| "missing required argument \"url\"".to_string())?;
+
+        client.get(url).send().await
+            .map_err(|err| err.to_string())?
+            .text().await
+            .map_err(|err| err.to_string())
+    }
+}
 
-    let r: LlamaResponse = match serde_json::from_str(&decoded)
+/// Build the set of enabled tools from the names passed on the command line.
+fn build_tool_registry(names: &[String]) -> Vec<Box<dyn Tool>>
+{
+    let mut registry: Vec<Box<dyn Tool>> = Vec::new();
+    for name in names
     {
-        Ok(val) => { val }
-        Err(err) => {
-            eprintln!("Unable to decode response:\n{0}", err.to_string());
-            return Err(err.to_string());
+        match name.as_str()
+        {
+            "shell" => registry.push(Box::new(ShellTool)),
+            "file_read" => registry.push(Box::new(FileReadTool)),
+            "http_fetch" => registry.push(Box::new(HttpFetchTool)),
+            other => eprintln!("[33m⚠ Unknown tool \"{other}\", ignoring.[m"),
         }
+    }
+    registry
+}
+
+fn print_tool_list()
+{
+    println!("Locally implemented tools:
+  shell      ─ run a shell command and return its output
+  file_read  ─ read the contents of a local file
+  http_fetch ─ fetch the body of a URL over HTTP(S)");
+}
+
+fn find_tool<'a>(tools: &'a [Box<dyn Tool>], name: &str) -> Option<&'a dyn Tool>
+{
+    tools.iter().find(|t| t.definition().function.name == name).map(|t| t.as_ref())
+}
+
+fn tool_defs(tools: &[Box<dyn Tool>]) -> Option<Vec<ToolDef>>
+{
+    if tools.is_empty()
+    {
+        None
+    }
+    else
+    {
+        Some(tools.iter().map(|t| t.definition()).collect())
+    }
+}
+
+/// Sniff an image's magic bytes and return its MIME type, or `None` if it isn't a
+/// recognized image format.
+fn detect_image_mime(bytes: &[u8]) -> Option<&'static str>
+{
+    if bytes.starts_with(&[0x89, b'P', b'N', b'G']) { Some("image/png") }
+    else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) { Some("image/jpeg") }
+    else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") { Some("image/gif") }
+    else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" { Some("image/webp") }
+    else if bytes.starts_with(b"BM") { Some("image/bmp") }
+    else { None }
+}
+
+/// A short, non-cryptographic content hash used so users can confirm what was sent.
+fn content_hash(bytes: &[u8]) -> String
+{
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Read, type-check, and base64-encode each image path for attachment to a message.
+fn encode_images(paths: &[String], json_mode: bool) -> Result<Vec<String>, String>
+{
+    let mut encoded = Vec::new();
+    for path in paths
+    {
+        let bytes = std::fs::read(path).map_err(|err| format!("{path}: {err}"))?;
+        let mime = detect_image_mime(&bytes)
+            .ok_or_else(|| format!("{path}: not a recognized image type (png/jpeg/gif/webp/bmp)"))?;
+        report_notice(json_mode, &format!("[33m✔ Attached {path} ({mime}, hash:{})[m", content_hash(&bytes)));
+        encoded.push(base64::engine::general_purpose::STANDARD.encode(&bytes));
+    }
+    Ok(encoded)
+}
+
+#[derive(Serialize)]
+struct SavedSessionRef<'a>
+{
+    model: &'a str,
+    messages: &'a [Message],
+}
+
+#[derive(Deserialize)]
+struct SavedSession
+{
+    model: String,
+    messages: Vec<Message>,
+}
+
+/// Directory sessions are saved to/loaded from: `<user data dir>/rust-llama-api/sessions`.
+fn sessions_dir() -> std::path::PathBuf
+{
+    let mut dir = dirs::data_dir().unwrap_or_else(|| std::path::PathBuf::from("."));
+    dir.push("rust-llama-api");
+    dir.push("sessions");
+    dir
+}
+
+/// Reject session names that could escape the sessions directory, e.g. via a path separator or
+/// a `..` component.
+fn validate_session_name(name: &str) -> Result<(), String>
+{
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\'])
+    {
+        return Err(format!("\"{name}\" is not a valid session name"));
+    }
+    Ok(())
+}
+
+fn session_path(name: &str) -> std::path::PathBuf
+{
+    let mut path = sessions_dir();
+    path.push(format!("{name}.msgpack"));
+    path
+}
+
+/// Serialize the model name and message history to a MessagePack file under the sessions dir.
+fn save_session(name: &str, req: &LlamaRequest) -> Result<(), String>
+{
+    validate_session_name(name)?;
+
+    let dir = sessions_dir();
+    std::fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+
+    let saved = SavedSessionRef {
+        model: &req.model,
+        messages: &req.messages,
     };
+    let bytes = rmp_serde::to_vec(&saved).map_err(|err| err.to_string())?;
+    std::fs::write(session_path(name), bytes).map_err(|err| err.to_string())
+}
 
-    // Everything should be serializable so no error expected
-    return Ok(r.message.content);
+fn load_session(name: &str) -> Result<SavedSession, String>
+{
+    validate_session_name(name)?;
+
+    let bytes = std::fs::read(session_path(name)).map_err(|err| err.to_string())?;
+    rmp_serde::from_slice(&bytes).map_err(|err| err.to_string())
 }
 
-fn individual_request_ch(request_object: &LlamaRequest, endpoint: String, sender: Sender<ChannelMessage>)
+/// Derive the Ollama server's base URL (e.g. `http://localhost:11434`) from the `--endpoint`
+/// argument, which points at the `/api/chat` route specifically.
+fn api_base(endpoint: &str) -> String
 {
-    let data = serde_json::to_string(&request_object).unwrap();
+    match endpoint.find("/api/")
+    {
+        Some(idx) => endpoint[..idx].to_string(),
+        None => endpoint.trim_end_matches('/').to_string(),
+    }
+}
 
-    // Buffer to hold curl response data
-    let mut buf = Vec::new();
+#[derive(Deserialize)]
+struct TagModel
+{
+    name: String,
+    size: Option<u64>,
+    modified_at: Option<String>,
+}
 
-    let mut curl_easy = Easy::new();
-    curl_easy.url(&endpoint).unwrap();
+#[derive(Deserialize)]
+struct TagsResponse
+{
+    models: Vec<TagModel>,
+}
 
-    curl_easy.read_function(move |into| {
-        Ok(data.as_bytes().read(into).unwrap())
-    }).unwrap();
-    curl_easy.post(true).unwrap();
+/// List the models available on the local Ollama server via `GET /api/tags`.
+async fn list_models(client: &reqwest::Client, base: &str, json_mode: bool) -> Result<(), String>
+{
+    let tags: TagsResponse = client.get(format!("{base}/api/tags"))
+        .send().await.map_err(|err| err.to_string())?
+        .json().await.map_err(|err| err.to_string())?;
 
+    for model in tags.models
     {
-        let mut transfer = curl_easy.transfer();
-        transfer.write_function(|data| {
-            let cl = buf.len();
-            match std::str::from_utf8(&data)
+        let size = model.size
+            .map(|bytes| format!("{:.1} GB", bytes as f64 / 1_073_741_824.0))
+            .unwrap_or_default();
+        report_notice(json_mode, &format!("{:<30} {:>10}  {}", model.name, size, model.modified_at.unwrap_or_default()));
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct NamedModelRequest<'a>
+{
+    name: &'a str,
+    stream: bool,
+}
+
+#[derive(Deserialize)]
+struct PullProgress
+{
+    status: String,
+    completed: Option<u64>,
+    total: Option<u64>,
+}
+
+/// Download a model via `POST /api/pull`, streaming and printing progress percentages.
+async fn pull_model(client: &reqwest::Client, base: &str, name: &str, json_mode: bool) -> Result<(), String>
+{
+    let response = client.post(format!("{base}/api/pull"))
+        .json(&NamedModelRequest { name, stream: true })
+        .send().await.map_err(|err| err.to_string())?;
+
+    let mut body = response.bytes_stream();
+    let mut line_buf = String::new();
+
+    while let Some(chunk) = body.next().await
+    {
+        let bytes = chunk.map_err(|err| err.to_string())?;
+        line_buf.push_str(&String::from_utf8_lossy(&bytes));
+
+        while let Some(pos) = line_buf.find('\n')
+        {
+            let line: String = line_buf.drain(..=pos).collect();
+            let line = line.trim_end_matches('\n');
+            if line.is_empty()
             {
-                Ok(utf) => {
-                    match serde_json::from_str::<LlamaResponse>(utf)
+                continue;
+            }
+
+            match serde_json::from_str::<PullProgress>(line)
+            {
+                Ok(progress) => {
+                    match (progress.completed, progress.total)
                     {
-                        Ok(resp) => {
-                            sender.send(ChannelMessage {
-                                done: resp.done,
-                                chunk: resp.message.content.to_string(),
-                            }).unwrap();
-                        }
-                        Err(_) => {
-                            sender.send(ChannelMessage {
-                                done: false,
-                                chunk: "‚ò†".to_string()
-                            }).unwrap();
+                        (Some(completed), Some(total)) if total > 0 => {
+                            report_notice(json_mode, &format!("{} ({:.1}%)", progress.status, completed as f64 / total as f64 * 100.0));
                         }
+                        _ => report_notice(json_mode, &progress.status),
                     }
                 }
-                Err(_) => {
-                    sender.send(ChannelMessage {
-                        done: false,
-                        chunk: "ÔÅ±".to_string(),
-                    }).unwrap();
+                Err(_) => report_error(json_mode, &format!("Unable to decode pull progress line: {line}")),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct ShowResponse
+{
+    license: Option<String>,
+    parameters: Option<String>,
+    template: Option<String>,
+}
+
+/// Print a model's parameters and template via `POST /api/show`.
+async fn show_model(client: &reqwest::Client, base: &str, name: &str, json_mode: bool) -> Result<(), String>
+{
+    let show: ShowResponse = client.post(format!("{base}/api/show"))
+        .json(&NamedModelRequest { name, stream: false })
+        .send().await.map_err(|err| err.to_string())?
+        .json().await.map_err(|err| err.to_string())?;
+
+    if let Some(parameters) = show.parameters
+    {
+        report_notice(json_mode, &format!("Parameters:\n{parameters}"));
+    }
+    if let Some(template) = show.template
+    {
+        report_notice(json_mode, &format!("Template:\n{template}"));
+    }
+    if let Some(license) = show.license
+    {
+        report_notice(json_mode, &format!("License:\n{license}"));
+    }
+    Ok(())
+}
+
+/// Exponential backoff delay ahead of retry attempt `attempt` (1-indexed).
+fn backoff_delay(attempt: u32) -> Duration
+{
+    Duration::from_millis(200 * 2u64.saturating_pow(attempt.min(6)))
+}
+
+/// Handle a single line of NDJSON, printing/emitting its content, appending it to `full_message`,
+/// and recording the server's final response message once the server marks its response done.
+fn handle_ndjson_line(line: &str, full_message: &mut String, final_message: &mut Option<Message>, json_mode: bool)
+{
+    match serde_json::from_str::<LlamaResponse>(line)
+    {
+        Ok(resp) => {
+            if json_mode
+            {
+                if !resp.message.content.is_empty()
+                {
+                    emit_json(&JsonEvent::Token { content: &resp.message.content });
+                }
+            }
+            else
+            {
+                print!("{}", resp.message.content);
+                std::io::stdout().flush().unwrap();
+            }
+            full_message.push_str(&resp.message.content);
+            if resp.done
+            {
+                *final_message = Some(resp.message);
+            }
+        }
+        Err(_) => {
+            report_error(json_mode, &format!("Unable to decode streamed line: {line}"));
+        }
+    }
+}
+
+/// Feed newly-received bytes into `line_buf` and dispatch every complete NDJSON line they
+/// complete to [`handle_ndjson_line`], leaving any trailing partial line buffered for the next
+/// chunk. A single line may arrive split across several chunks; this is what reassembles them.
+fn feed_ndjson_chunk(bytes: &[u8], line_buf: &mut String, full_message: &mut String, final_message: &mut Option<Message>, json_mode: bool)
+{
+    line_buf.push_str(&String::from_utf8_lossy(bytes));
+    while let Some(pos) = line_buf.find('\n')
+    {
+        let line: String = line_buf.drain(..=pos).collect();
+        let line = line.trim_end_matches('\n');
+        if !line.is_empty()
+        {
+            handle_ndjson_line(line, full_message, final_message, json_mode);
+        }
+    }
+}
+
+/// Run a single streamed turn of `req` against `endpoint`, printing tokens as they arrive.
+/// Returns the assembled assistant text and the server's final (`done`) response message, which
+/// carries any tool calls the model requested and the timing/eval metadata.
+///
+/// Drives the NDJSON parse directly off the response body stream (no polling). If the
+/// connection drops mid-response or the request fails to even start, retries with exponential
+/// backoff up to `max_retries` times. The chat API has no mechanism to resume a dropped stream
+/// from where it left off, so a mid-response drop does discard whatever partial text had already
+/// been printed/buffered — but that discard is never silent: a newline closes off the partial
+/// text and an explicit notice reports how much was thrown away before the next attempt starts
+/// from scratch.
+async fn stream_turn(client: &reqwest::Client, req: &LlamaRequest, endpoint: &str, json_mode: bool, max_retries: u32) -> Result<(String, Option<Message>), String>
+{
+    let mut full_message = String::new();
+    let mut final_message = None;
+    let mut line_buf = String::new();
+    let mut attempt = 0;
+
+    loop
+    {
+        let response = match client.post(endpoint).json(req).send().await
+        {
+            Ok(resp) => resp,
+            Err(err) => {
+                attempt += 1;
+                if attempt > max_retries
+                {
+                    return Err(err.to_string());
+                }
+                report_notice(json_mode, &format!("[33m⚠ Connection failed, reconnecting (attempt {attempt}/{max_retries})…[m"));
+                tokio::time::sleep(backoff_delay(attempt)).await;
+                continue;
+            }
+        };
+
+        let mut body = response.bytes_stream();
+        let mut dropped = false;
+
+        loop
+        {
+            match body.next().await
+            {
+                Some(Ok(bytes)) => {
+                    feed_ndjson_chunk(&bytes, &mut line_buf, &mut full_message, &mut final_message, json_mode);
                 }
+                Some(Err(_)) => { dropped = true; break; }
+                None => { break; }
             }
-            buf.extend_from_slice(data);
-            Ok(buf.len() - cl)
-        }).unwrap();
+        }
+
+        if final_message.is_some() || !dropped
+        {
+            break;
+        }
 
-        match transfer.perform()
+        attempt += 1;
+        if attempt > max_retries
         {
-            Ok(_) => { () }
-            Err(msg) => { eprintln!("{}", msg) }
+            return Err("connection dropped mid-response and retries were exhausted".to_string());
+        }
+
+        if !full_message.is_empty()
+        {
+            if !json_mode
+            {
+                println!();
+            }
+            report_notice(json_mode, &format!(
+                "[33m⚠ Discarding {} character(s) of partial reply from the dropped attempt (the API has no resume mechanism).[m",
+                full_message.chars().count()
+            ));
+        }
+        report_notice(json_mode, &format!("[33m⚠ Connection dropped, reconnecting (attempt {attempt}/{max_retries})…[m"));
+        tokio::time::sleep(backoff_delay(attempt)).await;
+
+        full_message.clear();
+        line_buf.clear();
+        final_message = None;
+    }
+
+    if !json_mode
+    {
+        println!("");
+    }
+
+    Ok((full_message, final_message))
+}
+
+/// Dispatch each requested tool call against `tools` and return the resulting `tool` messages.
+async fn run_tool_calls(client: &reqwest::Client, calls: &[ToolCall], tools: &[Box<dyn Tool>]) -> Vec<Message>
+{
+    let mut messages = Vec::with_capacity(calls.len());
+    for call in calls
+    {
+        let name = &call.function.name;
+        let result = match find_tool(tools, name)
+        {
+            Some(tool) => tool.execute(client, &call.function.arguments).await
+                .unwrap_or_else(|err| format!("error: {err}")),
+            None => format!("error: tool \"{name}\" is not enabled"),
         };
+
+        messages.push(Message {
+            role: "tool".to_string(),
+            content: result,
+            ..Default::default()
+        });
     }
+    messages
+}
+
+/// Tool registry and iteration/retry/output settings shared across a request or conversation,
+/// grouped to keep the signatures of [`make_request`], [`request_loop`], and
+/// [`request_single_message`] manageable.
+struct ToolRunConfig
+{
+    tools: Vec<Box<dyn Tool>>,
+    max_tool_iterations: u32,
+    json_mode: bool,
+    max_retries: u32,
 }
 
-fn make_request(model_name: String, prompt: String, endpoint: &str) -> Result<(), String>
+async fn make_request(client: &reqwest::Client, model_name: String, prompt: String, endpoint: &str, images: Option<Vec<String>>, config: &ToolRunConfig) -> Result<(), String>
 {
     let mut req = LlamaRequest {
         model: model_name,
@@ -228,138 +775,222 @@ fn make_request(model_name: String, prompt: String, endpoint: &str) -> Result<()
         options: Some(ModelOptions {
             temperature: Some(0.8),
             ..Default::default()
-        })
+        }),
+        tools: tool_defs(&config.tools),
     };
 
     req.messages.push(Message {
         role: "user".to_string(),
         content: prompt,
+        images,
         ..Default::default()
     });
 
-    let (sender, receiver) = channel::<ChannelMessage>();
-    let mut full_message = String::new();
-    scope( |sc| 
-           {
-               let mut ep = String::new();
-               ep.push_str(endpoint);
-               let jh = sc.spawn(|| { individual_request_ch(&req, ep, sender) });
-
-               loop
-               {
-                   match receiver.try_recv()
-                   {
-                       Ok(val) => {
-                           print!("{}", val.chunk);
-                           std::io::stdout().flush().unwrap();
-                           full_message.push_str(&val.chunk);
-                           if val.done
-                           {
-                               break;
-                           }
-                       }
-                       Err(_) => { std::thread::sleep(Duration::from_millis(150)) }
-                   };
-               }
-               jh.join().unwrap();
-           });
-    println!("");
+    for _ in 0..config.max_tool_iterations
+    {
+        let (full_message, final_message) = stream_turn(client, &req, endpoint, config.json_mode, config.max_retries).await?;
+        let tool_calls = final_message.as_ref().and_then(|m| m.tool_calls.clone());
+        let content = full_message.trim().to_string();
+
+        req.messages.push(Message {
+            role: "assistant".to_string(),
+            content: content.clone(),
+            tool_calls: tool_calls.clone(),
+            ..Default::default()
+        });
+
+        match tool_calls
+        {
+            Some(calls) if !calls.is_empty() => {
+                req.messages.extend(run_tool_calls(client, &calls, &config.tools).await);
+            }
+            _ => {
+                if config.json_mode
+                {
+                    emit_final_message(&content, &final_message);
+                }
+                return Ok(())
+            }
+        }
+    }
 
+    let msg = format!("Reached the maximum of {} tool-call round-trips; stopping.", config.max_tool_iterations);
+    report_error(config.json_mode, &format!("[33m⚠ {msg}[m"));
     Ok(())
 }
 
-fn print_conv_help()
+fn print_conv_help(json_mode: bool)
 {
-    println!("Implemented commands are:
-  #exit ‚îÄ‚îÄ‚îÄ quit the conversation
-  #quit ‚îÄ‚îÄ‚îÄ alias for #exit
-  #reset ‚îÄ‚îÄ reset the conversation
-  #system ‚îÄ reset the conversation and change the system message
-  #status ‚îÄ print the conversation history
-  #repeat ‚îÄ regenerate the last response from AI / repeat the last message");
+    report_notice(json_mode, "Implemented commands are:
+  #exit ─── quit the conversation
+  #quit ─── alias for #exit
+  #reset ── reset the conversation
+  #system ─ reset the conversation and change the system message
+  #status ─ print the conversation history
+  #repeat ─ regenerate the last response from AI / repeat the last message
+  #image ── attach one or more images to the next message, e.g. #image a.png b.jpg
+  #save ─── save the conversation history under a name, e.g. #save my-chat
+  #load ─── replace the conversation history with a saved session, e.g. #load my-chat
+  #models ─ list the models available on the Ollama server
+  #pull ─── download a model, e.g. #pull llava:7b
+  #show ──── print a model's parameters and template, e.g. #show llava:7b");
 }
 
-fn request_single_message(req: &mut LlamaRequest, endpoint: &str)
+/// Run one user/assistant exchange, following the model's tool-call loop until it stops
+/// requesting tools or `max_tool_iterations` round-trips are exhausted.
+async fn request_single_message(client: &reqwest::Client, req: &mut LlamaRequest, endpoint: &str, config: &ToolRunConfig) -> Result<(), String>
 {
-    let (sender, receiver) = channel::<ChannelMessage>();
-    let mut full_message = String::new();
-    scope( |sc| 
-           {
-               let mut ep = String::new();
-               ep.push_str(endpoint);
-               let jh = sc.spawn(|| { individual_request_ch(&req, ep, sender) });
-
-               loop
-               {
-                   match receiver.try_recv()
-                   {
-                       Ok(val) => {
-                           print!("{}", val.chunk);
-                           std::io::stdout().flush().unwrap();
-                           full_message.push_str(&val.chunk);
-                           if val.done
-                           {
-                               break;
-                           }
-                       }
-                       Err(_) => { std::thread::sleep(Duration::from_millis(150)) }
-                   };
-               }
-               jh.join().unwrap();
-           });
-    println!("");
+    for iteration in 0..config.max_tool_iterations
+    {
+        let (full_message, final_message) = stream_turn(client, req, endpoint, config.json_mode, config.max_retries).await?;
+        let tool_calls = final_message.as_ref().and_then(|m| m.tool_calls.clone());
+        let content = full_message.trim().to_string();
 
-    req.messages.push(Message {
-        role: "assistant".to_string(),
-        content: full_message.trim().to_string(),
-        ..Default::default()
-    });
+        req.messages.push(Message {
+            role: "assistant".to_string(),
+            content: content.clone(),
+            tool_calls: tool_calls.clone(),
+            ..Default::default()
+        });
+
+        match tool_calls
+        {
+            Some(calls) if !calls.is_empty() => {
+                req.messages.extend(run_tool_calls(client, &calls, &config.tools).await);
+            }
+            _ => {
+                if config.json_mode
+                {
+                    emit_final_message(&content, &final_message);
+                }
+                return Ok(())
+            }
+        }
+
+        if iteration + 1 == config.max_tool_iterations
+        {
+            let msg = format!("Reached the maximum of {} tool-call round-trips; stopping.", config.max_tool_iterations);
+            report_error(config.json_mode, &format!("[33m⚠ {msg}[m"));
+        }
+    }
+
+    Ok(())
 }
 
 /// Make multiple prompts to the destination model.
-fn request_loop(model_name: String, endpoint: &str)
+async fn request_loop(client: &reqwest::Client, model_name: String, endpoint: &str, load: Option<String>, save: Option<String>, config: ToolRunConfig)
 {
     // Continuously update this object
     let mut req = LlamaRequest {
         model: model_name,
         stream: true,
         messages: Vec::new(),
-        options: None
+        options: None,
+        tools: tool_defs(&config.tools),
     };
+    let json_mode = config.json_mode;
+    let mut pending_images: Vec<String> = Vec::new();
+
+    if let Some(name) = &load
+    {
+        match load_session(name)
+        {
+            Ok(saved) => {
+                if saved.model != req.model
+                {
+                    report_notice(json_mode, &format!("[33m⚠ Saved session \"{name}\" was recorded with model \"{}\", not \"{}\".[m", saved.model, req.model));
+                }
+                req.messages = saved.messages;
+                report_notice(json_mode, &format!("[33m✔ Loaded session \"{name}\" ({} messages).[m", req.messages.len()));
+            }
+            Err(err) => { report_error(json_mode, &format!("Unable to load session \"{name}\": {err}")) }
+        }
+    }
 
     loop
     {
         let mut prompt = String::new();
 
-        print!("[32m‚û§ [m");
-
-        std::io::stdout().flush().unwrap();
+        if !json_mode
+        {
+            print!("[32m➤ [m");
+            std::io::stdout().flush().unwrap();
+        }
 
         std::io::stdin().read_line(&mut prompt)
             .expect("Expected user input but could not use STDIN.");
 
         match prompt[..].trim() {
+            cmd if cmd.starts_with("#image ") => {
+                for path in cmd["#image ".len()..].split_whitespace()
+                {
+                    pending_images.push(path.to_string());
+                }
+            }
+            cmd if cmd.starts_with("#save ") => {
+                let name = cmd["#save ".len()..].trim();
+                match save_session(name, &req)
+                {
+                    Ok(()) => { report_notice(json_mode, &format!("[33m✔ Saved session \"{name}\".[m")) }
+                    Err(err) => { report_error(json_mode, &format!("Unable to save session \"{name}\": {err}")) }
+                }
+            }
+            cmd if cmd.starts_with("#load ") => {
+                let name = cmd["#load ".len()..].trim();
+                match load_session(name)
+                {
+                    Ok(saved) => {
+                        if saved.model != req.model
+                        {
+                            report_notice(json_mode, &format!("[33m⚠ Saved session \"{name}\" was recorded with model \"{}\", not \"{}\".[m", saved.model, req.model));
+                        }
+                        req.messages = saved.messages;
+                        report_notice(json_mode, &format!("[33m✔ Loaded session \"{name}\" ({} messages).[m", req.messages.len()));
+                    }
+                    Err(err) => { report_error(json_mode, &format!("Unable to load session \"{name}\": {err}")) }
+                }
+            }
+            "#models" => {
+                if let Err(err) = list_models(client, &api_base(endpoint), json_mode).await
+                {
+                    report_error(json_mode, &format!("Unable to list models: {err}"))
+                }
+            }
+            cmd if cmd.starts_with("#pull ") => {
+                let name = cmd["#pull ".len()..].trim();
+                if let Err(err) = pull_model(client, &api_base(endpoint), name, json_mode).await
+                {
+                    report_error(json_mode, &format!("Unable to pull model \"{name}\": {err}"))
+                }
+            }
+            cmd if cmd.starts_with("#show ") => {
+                let name = cmd["#show ".len()..].trim();
+                if let Err(err) = show_model(client, &api_base(endpoint), name, json_mode).await
+                {
+                    report_error(json_mode, &format!("Unable to show model \"{name}\": {err}"))
+                }
+            }
             "#help" => {
-                print_conv_help();
+                print_conv_help(json_mode);
             }
             "#exit" => { break }
             "#quit" => { break }
-            "#clear" => { print!("[H[J[3J"); std::io::stdout().flush().unwrap() }
+            "#clear" => { if !json_mode { print!("[H[J[3J"); std::io::stdout().flush().unwrap() } }
             "#status" => {
                 for m in req.messages.iter()
                 {
-                    println!("{}: {}", m.role, m.content);
+                    report_notice(json_mode, &format!("{}: {}", m.role, m.content));
                 }
             }
 
             "#reset" => {
                 req.messages = Vec::new();
-                println!("[33m‚úî Conversation history reset.[m");
+                report_notice(json_mode, "[33m✔ Conversation history reset.[m");
             }
             "#system" => {
                 req.messages = Vec::new();
-                println!("[33m‚úî Conversation history reset.[m");
-                println!("Input the new system prompt.");
+                report_notice(json_mode, "[33m✔ Conversation history reset.[m");
+                report_notice(json_mode, "Input the new system prompt.");
                 let mut new_system = String::new();
                 std::io::stdin().read_line(&mut new_system)
                     .expect("Expected user input but could not use STDIN.");
@@ -371,13 +1002,16 @@ fn request_loop(model_name: String, endpoint: &str)
             }
             "#repeat" => {
                 req.messages.pop();
-                if req.messages.len() > 0
+                if !req.messages.is_empty()
                 {
-                    request_single_message(&mut req, endpoint);
+                    if let Err(err) = request_single_message(client, &mut req, endpoint, &config).await
+                    {
+                        report_error(json_mode, &format!("Error received: {err}"));
+                    }
                 }
                 else
                 {
-                    println!("[33m‚ö† No conversation history.[m");
+                    report_notice(json_mode, "[33m⚠ No conversation history.[m");
                 }
             }
             _ => {
@@ -405,22 +1039,91 @@ fn request_loop(model_name: String, endpoint: &str)
                         }
                     }
                 }
+                let images = if pending_images.is_empty()
+                {
+                    None
+                }
+                else
+                {
+                    match encode_images(&pending_images, json_mode)
+                    {
+                        Ok(encoded) => { pending_images.clear(); Some(encoded) }
+                        Err(err) => { report_error(json_mode, &err); pending_images.clear(); None }
+                    }
+                };
+
                 req.messages.push(Message {
                     role: "user".to_string(),
                     content: prompt,
+                    images,
                     ..Default::default()
                 });
 
-                request_single_message(&mut req, endpoint);
+                if let Err(err) = request_single_message(client, &mut req, endpoint, &config).await
+                {
+                    report_error(json_mode, &format!("Error received: {err}"));
+                }
             }
         };
     }
+
+    if let Some(name) = &save
+    {
+        match save_session(name, &req)
+        {
+            Ok(()) => { report_notice(json_mode, &format!("[33m✔ Saved session \"{name}\".[m")) }
+            Err(err) => { report_error(json_mode, &format!("Unable to save session \"{name}\": {err}")) }
+        }
+    }
+}
+
+/// Report an error either as plain text on stderr or as a JSON error event on stdout,
+/// depending on `--json`.
+fn report_error(json_mode: bool, message: &str)
+{
+    if json_mode
+    {
+        emit_json(&JsonEvent::Error { message });
+    }
+    else
+    {
+        eprintln!("{message}");
+    }
+}
+
+/// Report a non-fatal status update either as plain text on stdout or as a JSON notice event on
+/// stdout, depending on `--json`.
+fn report_notice(json_mode: bool, message: &str)
+{
+    if json_mode
+    {
+        emit_json(&JsonEvent::Notice { message });
+    }
+    else
+    {
+        println!("{message}");
+    }
 }
 
-fn main()
+#[tokio::main]
+async fn main()
 {
     let args = InputOptions::parse();
 
+    if args.list_tools
+    {
+        print_tool_list();
+        return;
+    }
+
+    let config = ToolRunConfig {
+        tools: build_tool_registry(&args.tool),
+        max_tool_iterations: args.max_tool_iterations,
+        json_mode: args.json,
+        max_retries: args.max_retries,
+    };
+    let client = reqwest::Client::new();
+
     let mut prompt = String::new();
 
     // Depending on mode, perform certain actions
@@ -431,7 +1134,7 @@ fn main()
 
     if args.conv
     {
-        request_loop(args.model, &args.endpoint[..]);
+        request_loop(&client, args.model, &args.endpoint[..], args.load, args.save, config).await;
     }
     else
     {
@@ -444,7 +1147,7 @@ fn main()
                 match std::io::stdin().read_to_string(&mut prompt)
                 {
                     Ok(_) => { () }
-                    Err(err) => { eprintln!("Error reading stdin: {err}"); return }
+                    Err(err) => { report_error(args.json, &format!("Error reading stdin: {err}")); return }
                 };
             }
             else
@@ -453,11 +1156,11 @@ fn main()
                 let mut fhandle = match File::open(&fname)
                 {
                     Ok(f) => { f }
-                    Err(msg) => { 
+                    Err(msg) => {
                         match msg.kind()
                         {
-                            std::io::ErrorKind::NotFound => { eprintln!("File {fname} not found.") }
-                            _ => { eprintln!("{msg}") }
+                            std::io::ErrorKind::NotFound => { report_error(args.json, &format!("File {fname} not found.")) }
+                            _ => { report_error(args.json, &msg.to_string()) }
                         };
                         return }
                 };
@@ -466,20 +1169,119 @@ fn main()
         }
         else if args.prompt
         {
-            print!("Enter your prompt on a single line:\n>");
-            std::io::stdout().flush().unwrap();
+            if !args.json
+            {
+                print!("Enter your prompt on a single line:\n>");
+                std::io::stdout().flush().unwrap();
+            }
             std::io::stdin().read_line(&mut prompt).expect("Expected user input but could not use STDIN.");
         }
         else
         {
-            eprintln!("--file or --prompt are required parameters.");
+            report_error(args.json, "--file or --prompt are required parameters.");
             return;
         }
 
-        match make_request(args.model, prompt, &args.endpoint[..])
+        let images = if args.image.is_empty()
+        {
+            None
+        }
+        else
+        {
+            match encode_images(&args.image, args.json)
+            {
+                Ok(encoded) => { Some(encoded) }
+                Err(err) => { report_error(args.json, &err); return }
+            }
+        };
+
+        match make_request(&client, args.model, prompt, &args.endpoint[..], images, &config).await
         {
             Ok(res) => { () }
-            Err(err) => { eprintln!("Error received: {err}"); return }
+            Err(err) => { report_error(args.json, &format!("Error received: {err}")); return }
         }
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn backoff_delay_grows_exponentially_and_caps_out()
+    {
+        assert_eq!(backoff_delay(0), Duration::from_millis(200));
+        assert_eq!(backoff_delay(1), Duration::from_millis(400));
+        assert_eq!(backoff_delay(3), Duration::from_millis(1600));
+        // attempt is clamped at 6 doublings, so further attempts don't keep growing.
+        assert_eq!(backoff_delay(6), backoff_delay(20));
+    }
+
+    #[test]
+    fn detect_image_mime_recognizes_known_formats()
+    {
+        assert_eq!(detect_image_mime(&[0x89, b'P', b'N', b'G']), Some("image/png"));
+        assert_eq!(detect_image_mime(&[0xFF, 0xD8, 0xFF]), Some("image/jpeg"));
+        assert_eq!(detect_image_mime(b"GIF89a"), Some("image/gif"));
+        assert_eq!(detect_image_mime(b"BMxxxx"), Some("image/bmp"));
+        assert_eq!(detect_image_mime(b"not an image"), None);
+    }
+
+    #[test]
+    fn api_base_strips_the_known_chat_route()
+    {
+        assert_eq!(api_base("http://localhost:11434/api/chat"), "http://localhost:11434");
+    }
+
+    #[test]
+    fn api_base_falls_back_to_trimming_a_trailing_slash()
+    {
+        assert_eq!(api_base("http://localhost:11434/"), "http://localhost:11434");
+        assert_eq!(api_base("http://localhost:11434"), "http://localhost:11434");
+    }
+
+    #[test]
+    fn handle_ndjson_line_accumulates_content_and_captures_the_final_message()
+    {
+        let mut full_message = String::new();
+        let mut final_message = None;
+
+        handle_ndjson_line(
+            r#"{"model":"m","created_at":"t","message":{"role":"assistant","content":"Hel"},"done":false}"#,
+            &mut full_message, &mut final_message, true,
+        );
+        handle_ndjson_line(
+            r#"{"model":"m","created_at":"t","message":{"role":"assistant","content":"lo"},"done":true}"#,
+            &mut full_message, &mut final_message, true,
+        );
+
+        assert_eq!(full_message, "Hello");
+        assert!(final_message.is_some());
+    }
+
+    /// Regression test for a response whose NDJSON line is split across two network chunks
+    /// mid-line: feed_ndjson_chunk must buffer the partial line rather than hand a truncated,
+    /// unparsable fragment to handle_ndjson_line.
+    #[test]
+    fn feed_ndjson_chunk_reassembles_a_line_split_across_chunks()
+    {
+        let line = r#"{"model":"m","created_at":"t","message":{"role":"assistant","content":"hello"},"done":true}"#;
+        let (first, second) = line.split_at(line.len() / 2);
+
+        let mut line_buf = String::new();
+        let mut full_message = String::new();
+        let mut final_message = None;
+
+        feed_ndjson_chunk(first.as_bytes(), &mut line_buf, &mut full_message, &mut final_message, false);
+        // The line hasn't been terminated by a newline yet, so nothing should have been parsed.
+        assert!(full_message.is_empty());
+        assert!(final_message.is_none());
+
+        feed_ndjson_chunk(format!("{second}\n").as_bytes(), &mut line_buf, &mut full_message, &mut final_message, false);
+
+        assert_eq!(full_message, "hello");
+        assert!(final_message.is_some());
+        assert!(line_buf.is_empty());
+    }
+}